@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::crawler::StarRecord;
+
+/// A repo's cached stargazer series, keyed by repo name in [`Cache`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct CacheEntry {
+    /// `ETag` of the last successful repo metadata (star count) response,
+    /// sent back as `If-None-Match` so an unchanged repo costs a single
+    /// `304` instead of a full re-crawl.
+    pub(crate) etag: Option<String>,
+    pub(crate) fetched_at: String,
+    pub(crate) records: Vec<StarRecord>,
+}
+
+pub(crate) type Cache = HashMap<String, CacheEntry>;
+
+/// Load the on-disk cache, starting empty if it doesn't exist yet.
+pub(crate) fn load(path: &Path) -> Result<Cache> {
+    if !path.exists() {
+        return Ok(Cache::new());
+    }
+    let json =
+        std::fs::read_to_string(path).context(format!("Failed to read cache file {path:?}"))?;
+    serde_json::from_str(&json).context(format!("Failed to parse cache file {path:?}"))
+}
+
+/// Persist the cache to disk as pretty JSON.
+pub(crate) fn save(path: &Path, cache: &Cache) -> Result<()> {
+    let json = serde_json::to_string_pretty(cache)?;
+    std::fs::write(path, json).context(format!("Failed to write cache file {path:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// A missing cache file (first run) loads as an empty cache rather
+    /// than an error.
+    fn test_load_missing_file() {
+        let path = std::env::temp_dir()
+            .join(format!("star-history-cache-missing-{}", std::process::id()));
+        assert!(!path.exists());
+        assert!(load(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    /// Saving a cache and loading it back yields the same entries.
+    fn test_save_load_round_trip() {
+        let path = std::env::temp_dir()
+            .join(format!("star-history-cache-round-trip-{}", std::process::id()));
+
+        let mut cache = Cache::new();
+        cache.insert(
+            "rust-lang/rust".to_string(),
+            CacheEntry {
+                etag: Some("\"abc123\"".to_string()),
+                fetched_at: "2024-01-01".to_string(),
+                records: vec![StarRecord::new("2024-01-01".to_string(), 42)],
+            },
+        );
+
+        save(&path, &cache).unwrap();
+        let loaded = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, cache);
+    }
+}
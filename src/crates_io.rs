@@ -0,0 +1,89 @@
+use anyhow::{Context, Result};
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use serde::Serialize;
+use serde_json::Value;
+
+const CRATES_IO_URL: &str = "https://crates.io/api/v1/crates";
+
+/// A published crate's download counts on crates.io.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub(crate) struct Downloads {
+    /// All-time download count.
+    pub(crate) all_time: u64,
+    /// Downloads over crates.io's trailing 90-day window.
+    pub(crate) recent: u64,
+}
+
+/// Look up a published crate's download counts on crates.io.
+///
+/// Returns `Ok(None)` when no crate with this name exists, since most
+/// `tools.json` entries aren't published to crates.io at all.
+pub(crate) async fn download_counts(
+    client: &reqwest::Client,
+    name: &str,
+) -> Result<Option<Downloads>> {
+    let mut headers = HeaderMap::new();
+    // crates.io requires a descriptive user-agent on every request.
+    headers.insert(USER_AGENT, HeaderValue::from_static("star-history"));
+
+    let response = client
+        .get(format!("{CRATES_IO_URL}/{name}"))
+        .headers(headers)
+        .send()
+        .await
+        .context("Failed to reach crates.io")?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    let data: Value = response.json().await.context("Invalid crates.io response")?;
+    parse_downloads(&data).map(Some)
+}
+
+/// Parse a crates.io `GET /crates/{name}` response body into `Downloads`.
+fn parse_downloads(data: &Value) -> Result<Downloads> {
+    let all_time = data
+        .pointer("/crate/downloads")
+        .and_then(Value::as_u64)
+        .context("No downloads found in crates.io response")?;
+    let recent = data
+        .pointer("/crate/recent_downloads")
+        .and_then(Value::as_u64)
+        .context("No recent_downloads found in crates.io response")?;
+    Ok(Downloads { all_time, recent })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// `crate.downloads` and `crate.recent_downloads` both get carried
+    /// through into the `Downloads` struct.
+    fn test_parse_downloads() {
+        let data = serde_json::json!({
+            "crate": {
+                "downloads": 123_456,
+                "recent_downloads": 789,
+            },
+        });
+
+        let downloads = parse_downloads(&data).unwrap();
+        assert_eq!(downloads.all_time, 123_456);
+        assert_eq!(downloads.recent, 789);
+    }
+
+    #[test]
+    /// A response missing `recent_downloads` (an API change, or a
+    /// malformed mock) is an error rather than silently defaulting to 0.
+    fn test_parse_downloads_missing_recent_downloads() {
+        let data = serde_json::json!({
+            "crate": {
+                "downloads": 123_456,
+            },
+        });
+
+        assert!(parse_downloads(&data).is_err());
+    }
+}
@@ -1,24 +1,50 @@
-use std::fmt::{Display, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 
 use anyhow::{anyhow, Context, Result};
 use futures::future::join_all;
 use reqwest::Response;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 use time::OffsetDateTime;
 
-use crate::github::Github;
+use crate::cache::CacheEntry;
+use crate::provider::{StarCountOutcome, StarProvider};
 use crate::{date, STARGAZERS_PER_PAGE};
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) struct StarRecord {
     date: String,
     count: usize,
 }
 
+impl StarRecord {
+    pub(crate) fn new(date: String, count: usize) -> Self {
+        Self { date, count }
+    }
+
+    pub(crate) fn date(&self) -> &str {
+        &self.date
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.count
+    }
+}
+
+/// Result of crawling a single repo: either the cached series came back
+/// unchanged (a `304` on the cached `ETag`), or we fetched a fresh series
+/// along with the `ETag` to cache for next time.
+#[derive(Debug)]
+pub(crate) enum CrawlOutcome {
+    Unchanged(Vec<StarRecord>),
+    Fresh {
+        records: Vec<StarRecord>,
+        etag: Option<String>,
+    },
+}
+
 #[derive(Debug, Deserialize)]
-struct Star {
-    starred_at: String,
+pub(crate) struct Star {
+    pub(crate) starred_at: String,
 }
 
 /// Get the total page count from the link header.
@@ -27,9 +53,11 @@ fn get_page_count(response: &Response) -> Result<usize> {
         .headers()
         .get("link")
         .context("No link header found. Headers: {response.headers():#?}")?;
-    // Extract the last page number from the link header
+    // Extract the last page number from the link header. `page` isn't
+    // always the last query param (GitLab's `Link` header puts it before
+    // `per_page`), so match it preceded by either `?` or `&`.
     let last_page =
-        regex::Regex::new(r#"next.*&page=(\d*).*last"#)?.captures(link_header.to_str()?);
+        regex::Regex::new(r#"next.*[?&]page=(\d*).*last"#)?.captures(link_header.to_str()?);
 
     let mut page_count = 1;
     if let Some(last_page) = last_page {
@@ -63,29 +91,38 @@ fn get_request_pages(page_count: usize, max_requests_count: usize) -> Vec<usize>
     }
 }
 
-#[derive(Debug)]
 pub(crate) struct Crawler {
-    github: Github,
+    provider: Box<dyn StarProvider + Send + Sync>,
     max_request_count: usize,
+    use_graphql: bool,
+}
+
+impl Debug for Crawler {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Crawler")
+            .field("owner", &self.provider.owner())
+            .field("max_request_count", &self.max_request_count)
+            .field("use_graphql", &self.use_graphql)
+            .finish()
+    }
 }
 
 impl Display for Crawler {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.github.owner)
+        write!(f, "{}", self.provider.owner())
     }
 }
 
 impl Crawler {
-    pub(crate) fn new<T: Into<String>>(
-        owner: T,
-        repo: T,
-        token: String,
+    pub(crate) fn new(
+        provider: Box<dyn StarProvider + Send + Sync>,
         max_request_count: usize,
+        use_graphql: bool,
     ) -> Self {
-        let github = Github::new(owner, repo, token);
         Self {
-            github,
+            provider,
             max_request_count,
+            use_graphql,
         }
     }
 
@@ -98,7 +135,7 @@ impl Crawler {
         for (index, response) in request_pages.iter().zip(responses.into_iter()) {
             match response {
                 Ok(response) => {
-                    let json: Vec<Star> = response.json().await?;
+                    let json = self.provider.parse_stargazers(response).await?;
                     if let Some(star) = json.get(0) {
                         let starred_at = date::iso8601_to_ymd(&star.starred_at)?;
                         star_records.push(StarRecord {
@@ -127,14 +164,7 @@ impl Crawler {
                 return Err(anyhow!("Response status: {}", response.status()));
             }
 
-            let new_stars: Vec<Star> = response
-                .json::<Vec<Star>>()
-                .await?
-                .into_iter()
-                .map(|r| Star {
-                    starred_at: r.starred_at,
-                })
-                .collect();
+            let new_stars = self.provider.parse_stargazers(response).await?;
             stars.extend(new_stars);
         }
 
@@ -151,8 +181,30 @@ impl Crawler {
         Ok(star_records)
     }
 
-    pub(crate) async fn stars(&self) -> Result<Vec<StarRecord>> {
-        let response = self.github.stargazers(None).await?;
+    pub(crate) async fn stars(&self, cached: Option<&CacheEntry>) -> Result<CrawlOutcome> {
+        if self.use_graphql {
+            if let Some((star_records, count)) =
+                self.provider.graphql_stars(self.max_request_count).await?
+            {
+                return Self::finalize(star_records, count, None);
+            }
+        }
+
+        // The stargazers listing is oldest-first, so its first page is
+        // immutable for a growing repo and useless as a freshness signal.
+        // The repo metadata endpoint behind `star_count` changes its `ETag`
+        // the instant the star count does, so key the conditional request
+        // off that instead.
+        let etag = cached.and_then(|entry| entry.etag.as_deref());
+        let (star_count, etag) = match self.provider.star_count(etag).await? {
+            StarCountOutcome::Unchanged => {
+                let cached = cached.context("Got 304 Not Modified but have no cached records")?;
+                return Ok(CrawlOutcome::Unchanged(cached.records.clone()));
+            }
+            StarCountOutcome::Fresh { count, etag } => (count, etag),
+        };
+
+        let response = self.provider.stargazers(None).await?;
 
         // If response status is not 200, then return an error.
         if response.status() != 200 {
@@ -161,55 +213,58 @@ impl Crawler {
 
         let page_count = get_page_count(&response)?;
 
-        let json: Vec<Star> = response.json().await?;
+        let json = self.provider.parse_stargazers(response).await?;
         if page_count == 1 && json.is_empty() {
             // No stargazers
-            return Ok(vec![]);
+            return Ok(CrawlOutcome::Fresh {
+                records: vec![],
+                etag,
+            });
         }
 
         let request_pages = get_request_pages(page_count, self.max_request_count);
         let responses = join_all(
             request_pages
                 .iter()
-                .map(|page| self.github.stargazers(Some(*page))),
+                .map(|page| self.provider.stargazers(Some(*page))),
         )
         .await;
 
-        let mut star_records = if request_pages.len() < self.max_request_count {
+        let star_records = if request_pages.len() < self.max_request_count {
             self.parse_all_star_responses(responses).await?
         } else {
             self.sample_star_responses(request_pages, responses).await?
         };
 
+        Self::finalize(star_records, star_count, etag)
+    }
+
+    /// Sort the sampled records and, if the newest sample is stale (or there
+    /// are none), append the already-known current total as of now.
+    fn finalize(
+        mut star_records: Vec<StarRecord>,
+        current_count: usize,
+        etag: Option<String>,
+    ) -> Result<CrawlOutcome> {
         star_records.sort();
 
         let now = OffsetDateTime::now_utc();
-        let add_current_stars = if star_records.is_empty() {
-            true
-        } else {
-            let starred_at = &star_records[star_records.len() - 1].date;
-            let last_date = date::parse_ymd(starred_at.as_str())?;
-            (now.date() - last_date) > time::Duration::days(90)
+        let add_current_stars = match star_records.last() {
+            None => true,
+            Some(last) => {
+                let last_date = date::parse_ymd(last.date.as_str())?;
+                (now.date() - last_date) > time::Duration::days(90) || last.count != current_count
+            }
         };
 
         if add_current_stars {
-            let count = self.star_count().await?;
             let starred_at = date::format_ymd(now);
-            star_records.push(StarRecord {
-                date: starred_at,
-                count,
-            });
+            star_records.push(StarRecord::new(starred_at, current_count));
         }
 
-        Ok(star_records)
-    }
-
-    async fn star_count(&self) -> Result<usize> {
-        let data: Value = self.github.star_count().await?.json().await?;
-
-        let value = data
-            .get("stargazers_count")
-            .context("No stargazers_count found")?;
-        Ok(serde_json::from_value(value.clone())?)
+        Ok(CrawlOutcome::Fresh {
+            records: star_records,
+            etag,
+        })
     }
 }
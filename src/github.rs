@@ -1,93 +1,187 @@
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
 use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
 use reqwest::{
     header::{HeaderMap, HeaderValue},
     Request, Response,
 };
+use serde_json::Value;
 use time::OffsetDateTime;
 
+use crate::crawler::{Star, StarRecord};
+use crate::date;
+use crate::provider::{StarCountOutcome, StarProvider};
 use crate::STARGAZERS_PER_PAGE;
 
+const GRAPHQL_URL: &str = "https://api.github.com/graphql";
+
+// Pre-emptively wait for the rate limit to reset once remaining requests
+// drop to or below this, instead of racing ahead into a 403.
+const RATE_LIMIT_THRESHOLD: usize = 5;
+
+const STARGAZERS_QUERY: &str = r#"
+query($owner: String!, $repo: String!, $cursor: String) {
+  repository(owner: $owner, name: $repo) {
+    stargazers(first: 100, after: $cursor, orderBy: {field: STARRED_AT, direction: ASC}) {
+      totalCount
+      pageInfo {
+        endCursor
+        hasNextPage
+      }
+      edges {
+        starredAt
+      }
+    }
+  }
+}
+"#;
+
+/// Tracks the rate-limit budget reported by `x-ratelimit-*` response
+/// headers, so we can wait out a reset before it's forced on us by a 403.
+///
+/// GitHub's rate limit is per-token, not per-repo, so callers crawling
+/// several repos concurrently must share one `Arc<RateLimit>` across all of
+/// their [`Github`] instances for `wait_for_budget` to actually smooth the
+/// concurrent crawl, instead of each instance starting back at `usize::MAX`.
+#[derive(Debug)]
+pub(crate) struct RateLimit {
+    remaining: AtomicUsize,
+    reset: AtomicI64,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            remaining: AtomicUsize::new(usize::MAX),
+            reset: AtomicI64::new(0),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Github {
     client: reqwest::Client,
     pub(crate) owner: String,
     pub(crate) repo: String,
     token: String,
+    rate_limit: Arc<RateLimit>,
 }
 
 impl Github {
-    pub(crate) fn new<T: Into<String>>(owner: T, repo: T, token: String) -> Self {
+    pub(crate) fn new<T: Into<String>>(
+        owner: T,
+        repo: T,
+        token: String,
+        rate_limit: Arc<RateLimit>,
+    ) -> Self {
         let client = reqwest::Client::new();
         Self {
             client,
             owner: owner.into(),
             repo: repo.into(),
             token,
+            rate_limit,
         }
     }
 
-    /// Get the total star count for the repo.
-    pub(crate) async fn star_count(&self) -> Result<Response> {
-        self.api_call(format!(
-            "https://api.github.com/repos/{owner}/{repo}",
-            owner = self.owner,
-            repo = self.repo,
-        ))
-        .await
+    /// Record the remaining-quota headers from a response for future calls
+    /// to consult.
+    fn record_rate_limit(&self, response: &Response) {
+        if let Some(remaining) = response
+            .headers()
+            .get("x-ratelimit-remaining")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+        {
+            self.rate_limit.remaining.store(remaining, Ordering::SeqCst);
+        }
+        if let Some(reset) = response
+            .headers()
+            .get("x-ratelimit-reset")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+        {
+            self.rate_limit.reset.store(reset, Ordering::SeqCst);
+        }
     }
 
-    /// Get all individual stargazers for the repo on the given page.
-    pub(crate) async fn stargazers(&self, page: Option<usize>) -> Result<Response> {
-        let mut url = format!(
-            "https://api.github.com/repos/{owner}/{repo}/stargazers?per_page={STARGAZERS_PER_PAGE}",
-            owner = self.owner,
-            repo = self.repo,
-            STARGAZERS_PER_PAGE = STARGAZERS_PER_PAGE,
-        );
-        if let Some(page) = page {
-            url = format!("{url}&page={page}");
+    /// If the last known remaining quota is at or below the threshold,
+    /// sleep until the reset time instead of racing into a 403.
+    async fn wait_for_budget(&self) {
+        if self.rate_limit.remaining.load(Ordering::SeqCst) > RATE_LIMIT_THRESHOLD {
+            return;
         }
-        self.api_call(url).await
+
+        let reset = self.rate_limit.reset.load(Ordering::SeqCst);
+        let Ok(reset) = OffsetDateTime::from_unix_timestamp(reset) else {
+            return;
+        };
+        let now = OffsetDateTime::now_utc();
+        if reset <= now {
+            return;
+        }
+
+        let wait = (reset - now).unsigned_abs();
+        println!("Rate limit budget low, pre-emptively waiting {wait:?} until reset at {reset}...");
+        tokio::time::sleep(wait).await;
     }
 
-    /// Make a single request, respecting the rate limit.
+    /// How long to wait before retrying a rate-limited response.
     ///
-    /// If we get a 429, wait for the rate limit to reset.
-    /// Retry again in a loop until we get a non-rate-limited response.
-    ///
-    /// The `x-ratelimit-reset` header specifies the time at which the current
-    /// rate limit window resets in UTC epoch seconds (e.g. `x-ratelimit-reset: 1372700873`)
+    /// GitHub uses `Retry-After` for secondary rate limits, which takes
+    /// priority when present; otherwise fall back to `x-ratelimit-reset`.
+    fn retry_wait(response: &Response) -> Result<Duration> {
+        if let Some(retry_after) = response
+            .headers()
+            .get("retry-after")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+        {
+            return Ok(Duration::from_secs(retry_after));
+        }
+
+        let reset = response
+            .headers()
+            .get("x-ratelimit-reset")
+            .ok_or_else(|| anyhow!("Missing x-ratelimit-reset header"))?
+            .to_str()?
+            .parse::<i64>()?;
+        let reset = OffsetDateTime::from_unix_timestamp(reset)?;
+        let now = OffsetDateTime::now_utc();
+        Ok((reset - now).unsigned_abs())
+    }
+
+    /// Make a single request, respecting the rate limit.
     ///
-    /// Use `tokio::time::sleep` to wait until the rate limit resets.
+    /// Pre-emptively waits if our tracked budget is nearly exhausted. If we
+    /// still get a 429/403, wait out `Retry-After` (or the reset time) and
+    /// retry again in a loop until we get a non-rate-limited response.
     async fn handle_rate_limit(&self, request: Request) -> Result<Response> {
+        self.wait_for_budget().await;
+
         let mut response = self
             .client
             .execute(request.try_clone().context("Request can not be cloned")?)
             .await?;
+        self.record_rate_limit(&response);
+
         while response.status() == 429 || response.status() == 403 {
-            let reset = response
-                .headers()
-                .get("x-ratelimit-reset")
-                .ok_or_else(|| anyhow!("Missing x-ratelimit-reset header"))?
-                .to_str()?
-                .parse::<i64>()?;
-            let reset = OffsetDateTime::from_unix_timestamp(reset)?;
-            let now = OffsetDateTime::now_utc();
-
-            // Calculate duration to wait, in seconds
-            let wait = reset - now;
-
-            println!("Rate limit exceeded, waiting until reset at {reset} in {wait}...");
-            tokio::time::sleep(wait.unsigned_abs()).await;
+            let wait = Self::retry_wait(&response)?;
+            println!("Rate limit exceeded, waiting {wait:?} before retrying...");
+            tokio::time::sleep(wait).await;
             response = self
                 .client
                 .execute(request.try_clone().context("Request can not be cloned")?)
                 .await?;
+            self.record_rate_limit(&response);
         }
         Ok(response)
     }
 
-    async fn api_call(&self, url: String) -> Result<Response> {
+    async fn api_call(&self, url: String, etag: Option<&str>) -> Result<Response> {
         let mut headers = HeaderMap::new();
         headers.insert(
             reqwest::header::ACCEPT,
@@ -102,8 +196,228 @@ impl Github {
             reqwest::header::AUTHORIZATION,
             HeaderValue::from_str(&format!("token {}", self.token))?,
         );
+        if let Some(etag) = etag {
+            headers.insert(
+                reqwest::header::IF_NONE_MATCH,
+                HeaderValue::from_str(etag)?,
+            );
+        }
         println!("Calling {url}");
         let request = self.client.get(&url).headers(headers).build()?;
         self.handle_rate_limit(request).await
     }
+
+    /// Post a GraphQL v4 query, respecting the same auth and rate-limit
+    /// handling as `api_call`.
+    async fn graphql(&self, query: &str, variables: Value) -> Result<Value> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            reqwest::header::USER_AGENT,
+            HeaderValue::from_static("star-history"),
+        );
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("token {}", self.token))?,
+        );
+        println!("Calling GraphQL {GRAPHQL_URL}");
+        let request = self
+            .client
+            .post(GRAPHQL_URL)
+            .headers(headers)
+            .json(&serde_json::json!({ "query": query, "variables": variables }))
+            .build()?;
+        Ok(self.handle_rate_limit(request).await?.json().await?)
+    }
+}
+
+#[async_trait]
+impl StarProvider for Github {
+    fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// Get the total star count for the repo, conditional on `etag` if set.
+    ///
+    /// The repo metadata endpoint's `ETag` changes the instant the star
+    /// count does, unlike the `stargazers` listing's first page (oldest
+    /// stars first, so immutable for a growing repo), making this the right
+    /// thing to send `If-None-Match` against.
+    async fn star_count(&self, etag: Option<&str>) -> Result<StarCountOutcome> {
+        let response = self
+            .api_call(
+                format!(
+                    "https://api.github.com/repos/{owner}/{repo}",
+                    owner = self.owner,
+                    repo = self.repo,
+                ),
+                etag,
+            )
+            .await?;
+
+        if response.status() == 304 {
+            return Ok(StarCountOutcome::Unchanged);
+        }
+
+        let etag = response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned);
+
+        let data: Value = response.json().await?;
+        let value = data
+            .get("stargazers_count")
+            .context("No stargazers_count found")?;
+        let count = serde_json::from_value(value.clone())?;
+        Ok(StarCountOutcome::Fresh { count, etag })
+    }
+
+    /// Get all individual stargazers for the repo on the given page.
+    async fn stargazers(&self, page: Option<usize>) -> Result<Response> {
+        let mut url = format!(
+            "https://api.github.com/repos/{owner}/{repo}/stargazers?per_page={STARGAZERS_PER_PAGE}",
+            owner = self.owner,
+            repo = self.repo,
+            STARGAZERS_PER_PAGE = STARGAZERS_PER_PAGE,
+        );
+        if let Some(page) = page {
+            url = format!("{url}&page={page}");
+        }
+        self.api_call(url, None).await
+    }
+
+    async fn parse_stargazers(&self, response: Response) -> Result<Vec<Star>> {
+        Ok(response.json().await?)
+    }
+
+    /// Crawl stargazers via GraphQL: 100 per page (vs 30 for REST), and
+    /// `starredAt` timestamps plus the running `totalCount` come back in the
+    /// same payload, so callers need no separate `star_count` call for a
+    /// total.
+    ///
+    /// GraphQL's cursor only pages forward, unlike REST's `?page=N` which
+    /// can jump straight to an arbitrary offset, so there is no way to reach
+    /// an arbitrary later page without first walking every page before it.
+    /// To keep this mode's whole point — cutting request count for large
+    /// repos — the walk is capped at `max_request_count` pages, the same
+    /// budget the REST path spends; a repo with more history than that
+    /// covers only gets samples from its oldest `max_request_count * 100`
+    /// stargazers, the same trade-off the REST path makes by only ever
+    /// looking at the first star of each page it lands on.
+    async fn graphql_stars(
+        &self,
+        max_request_count: usize,
+    ) -> Result<Option<(Vec<StarRecord>, usize)>> {
+        let mut cursor: Option<String> = None;
+        let mut starred_ats: Vec<String> = Vec::new();
+        let mut total_count = 0;
+
+        for _ in 0..max_request_count {
+            let variables = serde_json::json!({
+                "owner": self.owner,
+                "repo": self.repo,
+                "cursor": cursor,
+            });
+            let data = self.graphql(STARGAZERS_QUERY, variables).await?;
+
+            let stargazers = data
+                .pointer("/data/repository/stargazers")
+                .context("Malformed GraphQL stargazers response")?;
+            total_count = stargazers
+                .get("totalCount")
+                .and_then(Value::as_u64)
+                .context("No totalCount in GraphQL stargazers response")? as usize;
+            let edges = stargazers
+                .get("edges")
+                .and_then(Value::as_array)
+                .context("No edges in GraphQL stargazers response")?;
+            for edge in edges {
+                if let Some(starred_at) = edge.get("starredAt").and_then(Value::as_str) {
+                    starred_ats.push(starred_at.to_string());
+                }
+            }
+
+            let page_info = stargazers
+                .get("pageInfo")
+                .context("No pageInfo in GraphQL stargazers response")?;
+            let has_next_page = page_info
+                .get("hasNextPage")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            cursor = page_info
+                .get("endCursor")
+                .and_then(Value::as_str)
+                .map(str::to_owned);
+
+            if !has_next_page || cursor.is_none() {
+                break;
+            }
+        }
+
+        let star_records = sample_starred_ats(&starred_ats, max_request_count)?;
+        Ok(Some((star_records, total_count)))
+    }
+}
+
+/// Sample a stride of evenly-spaced `StarRecord`s out of a chronologically
+/// ordered list of ISO 8601 `starredAt` timestamps.
+///
+/// Each timestamp's position in the list is exactly its cumulative star
+/// count at that point, so striding over it gives evenly-spaced, exact
+/// counts across whatever history was walked, instead of the coarse
+/// `STARGAZERS_PER_PAGE * page` estimate the REST path has to use.
+fn sample_starred_ats(starred_ats: &[String], max_request_count: usize) -> Result<Vec<StarRecord>> {
+    let mut star_records = Vec::new();
+    let mut index = 0;
+    while index < starred_ats.len() {
+        let starred_at = date::iso8601_to_ymd(&starred_ats[index])?;
+        star_records.push(StarRecord::new(starred_at, index + 1));
+        index += (starred_ats.len() / max_request_count).max(1);
+    }
+    Ok(star_records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// An empty stargazer history (a repo with zero stars) yields no
+    /// records.
+    fn test_sample_starred_ats_empty() {
+        let star_records = sample_starred_ats(&[], 10).unwrap();
+        assert!(star_records.is_empty());
+    }
+
+    #[test]
+    /// Each sample's count is its 1-based position in the chronological
+    /// list, strided by `len / max_request_count`.
+    fn test_sample_starred_ats_stride() {
+        let starred_ats: Vec<String> = (1..=10)
+            .map(|day| format!("2020-01-{day:02}T00:00:00Z"))
+            .collect();
+
+        let star_records = sample_starred_ats(&starred_ats, 5).unwrap();
+
+        assert_eq!(
+            star_records.iter().map(StarRecord::count).collect::<Vec<_>>(),
+            vec![1, 3, 5, 7, 9]
+        );
+    }
+
+    #[test]
+    /// A `max_request_count` larger than the history still samples every
+    /// entry once, rather than skipping via a zero stride.
+    fn test_sample_starred_ats_max_request_count_exceeds_history() {
+        let starred_ats: Vec<String> = (1..=3)
+            .map(|day| format!("2020-01-{day:02}T00:00:00Z"))
+            .collect();
+
+        let star_records = sample_starred_ats(&starred_ats, 100).unwrap();
+
+        assert_eq!(
+            star_records.iter().map(StarRecord::count).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+    }
 }
@@ -0,0 +1,149 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    Request, Response,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::crawler::Star;
+use crate::provider::{StarCountOutcome, StarProvider};
+use crate::STARGAZERS_PER_PAGE;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Gitlab {
+    client: reqwest::Client,
+    owner: String,
+    repo: String,
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Starrer {
+    starred_since: String,
+}
+
+impl Gitlab {
+    pub(crate) fn new<T: Into<String>>(owner: T, repo: T, token: String) -> Self {
+        let client = reqwest::Client::new();
+        Self {
+            client,
+            owner: owner.into(),
+            repo: repo.into(),
+            token,
+        }
+    }
+
+    /// GitLab identifies projects by a URL-encoded `namespace/project` path.
+    fn project_id(&self) -> String {
+        format!("{}%2F{}", self.owner, self.repo)
+    }
+
+    /// Make a single request, respecting the rate limit.
+    ///
+    /// GitLab reports secondary rate limits with a 429 and a `Retry-After`
+    /// header, so this retries only on 429, waiting for the advertised number
+    /// of seconds (60 if the header is missing). Unlike
+    /// `Github::handle_rate_limit`, there is no pre-emptive budget wait and no
+    /// fallback to a reset timestamp.
+    async fn handle_rate_limit(&self, request: Request) -> Result<Response> {
+        let mut response = self
+            .client
+            .execute(request.try_clone().context("Request can not be cloned")?)
+            .await?;
+        while response.status() == 429 {
+            let retry_after = response
+                .headers()
+                .get("retry-after")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok())
+                .unwrap_or(60);
+
+            println!("Rate limit exceeded, waiting {retry_after}s...");
+            tokio::time::sleep(std::time::Duration::from_secs(retry_after)).await;
+            response = self
+                .client
+                .execute(request.try_clone().context("Request can not be cloned")?)
+                .await?;
+        }
+        Ok(response)
+    }
+
+    async fn api_call(&self, url: String, etag: Option<&str>) -> Result<Response> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            reqwest::header::USER_AGENT,
+            HeaderValue::from_static("star-history"),
+        );
+        if !self.token.is_empty() {
+            headers.insert("PRIVATE-TOKEN", HeaderValue::from_str(&self.token)?);
+        }
+        if let Some(etag) = etag {
+            headers.insert(
+                reqwest::header::IF_NONE_MATCH,
+                HeaderValue::from_str(etag)?,
+            );
+        }
+        println!("Calling {url}");
+        let request = self.client.get(&url).headers(headers).build()?;
+        self.handle_rate_limit(request).await
+    }
+}
+
+#[async_trait]
+impl StarProvider for Gitlab {
+    fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// Get the total star count for the repo, conditional on `etag` if set.
+    async fn star_count(&self, etag: Option<&str>) -> Result<StarCountOutcome> {
+        let response = self
+            .api_call(
+                format!(
+                    "https://gitlab.com/api/v4/projects/{id}",
+                    id = self.project_id(),
+                ),
+                etag,
+            )
+            .await?;
+
+        if response.status() == 304 {
+            return Ok(StarCountOutcome::Unchanged);
+        }
+
+        let etag = response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned);
+
+        let data: Value = response.json().await?;
+        let value = data.get("star_count").context("No star_count found")?;
+        let count = serde_json::from_value(value.clone())?;
+        Ok(StarCountOutcome::Fresh { count, etag })
+    }
+
+    /// Get all individual stargazers for the repo on the given page.
+    async fn stargazers(&self, page: Option<usize>) -> Result<Response> {
+        let mut url = format!(
+            "https://gitlab.com/api/v4/projects/{id}/starrers?per_page={STARGAZERS_PER_PAGE}",
+            id = self.project_id(),
+        );
+        if let Some(page) = page {
+            url = format!("{url}&page={page}");
+        }
+        self.api_call(url, None).await
+    }
+
+    async fn parse_stargazers(&self, response: Response) -> Result<Vec<Star>> {
+        let starrers: Vec<Starrer> = response.json().await?;
+        Ok(starrers
+            .into_iter()
+            .map(|starrer| Star {
+                starred_at: starrer.starred_since,
+            })
+            .collect())
+    }
+}
@@ -19,13 +19,22 @@
 #![deny(missing_docs)]
 #![allow(clippy::module_name_repetitions)]
 
+mod cache;
+mod crates_io;
 mod crawler;
 mod date;
 mod github;
+mod gitlab;
+mod provider;
+mod report;
 mod repos;
 
 use anyhow::{Context, Result};
+use futures::future::join_all;
+use serde::Serialize;
+use std::sync::Arc;
 use std::{collections::HashMap, env, path::PathBuf};
+use tokio::sync::Semaphore;
 
 // Number of total requests
 const MAX_REQUEST_COUNT: usize = 10;
@@ -35,47 +44,228 @@ const TOOLS_JSON_URL: &str = "https://raw.githubusercontent.com/analysis-tools-d
 // Number of stargazers to fetch per page
 pub(crate) const STARGAZERS_PER_PAGE: usize = 30;
 
-use crate::crawler::Crawler;
+// Number of repos to crawl concurrently. All crawlers share a single
+// `GITHUB_TOKEN` and hence one rate-limit budget, so this bounds how many
+// overlap rather than being a hard concurrency target.
+const MAX_CONCURRENT_CRAWLS: usize = 8;
+
+// Where the incremental crawl cache (ETags + last-fetched star series) lives.
+const CACHE_PATH: &str = "star_cache.json";
+
+// Where the HTML/SVG star-history report is written, when enabled.
+const REPORT_DIR: &str = "report";
+
+// Number of concurrent crates.io lookups, when download-count enrichment is
+// enabled. crates.io asks anonymous clients to keep well under 1 req/s, so
+// this stays far more conservative than `MAX_CONCURRENT_CRAWLS`.
+const MAX_CONCURRENT_CRATES_IO_LOOKUPS: usize = 2;
+
+// Minimum current star count a tool needs to stay in the output. 0 (the
+// default) means this threshold isn't applied; a tool only needs to clear
+// one of `MIN_STARS`/`MIN_DOWNLOADS` that's actually set to a non-zero value.
+const MIN_STARS: usize = 0;
+
+// Minimum all-time crates.io download count a tool needs to stay in the
+// output, same "0 means not applied" rule as `MIN_STARS`. Only meaningful
+// when `ENRICH_CRATES_IO` is set; with no downloads data a tool falls back
+// to `MIN_STARS` alone.
+const MIN_DOWNLOADS: u64 = 0;
+
+use crate::cache::CacheEntry;
+use crate::crawler::{Crawler, CrawlOutcome, StarRecord};
+use crate::github::{Github, RateLimit};
+use crate::gitlab::Gitlab;
+use crate::provider::StarProvider;
+use crate::repos::Host;
 
 /// Save JSON to file
 fn save(path: &PathBuf, json: String) -> Result<()> {
     std::fs::write(path, json).context(format!("Failed to write JSON to file {path:?}"))
 }
 
+/// One repo's worth of output: its star-history series, plus crates.io
+/// download figures when `ENRICH_CRATES_IO` found a matching published crate.
+#[derive(Debug, Serialize)]
+pub(crate) struct RepoMetrics {
+    pub(crate) stars: Vec<StarRecord>,
+    pub(crate) downloads: Option<crates_io::Downloads>,
+}
+
 // Main function with error handling and tokio runtime
 #[tokio::main]
 async fn main() -> Result<()> {
     let token = env::var("GITHUB_TOKEN")
         .context("Github token MUST be set because we crawl a lot of repos")?;
+    // GitLab's public API works unauthenticated too, just at a lower rate limit.
+    let gitlab_token = env::var("GITLAB_TOKEN").unwrap_or_default();
 
     println!("Fetching all repos from {TOOLS_JSON_URL}...");
     let repos = repos::get_repos(TOOLS_JSON_URL).await?;
     println!("Found {} repos", repos.len());
 
-    let mut all_stars = HashMap::new();
+    let cache_path = PathBuf::from(CACHE_PATH);
+    let cache = Arc::new(cache::load(&cache_path)?);
+
+    // Use GitHub's GraphQL v4 API instead of paging the REST one: 100
+    // stargazers per request (vs 30) and no separate star-count call needed.
+    let use_graphql = env::var("USE_GRAPHQL_CRAWLER").is_ok();
+
+    let max_concurrent_crawls = env::var("MAX_CONCURRENT_CRAWLS")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(MAX_CONCURRENT_CRAWLS);
+    let semaphore = Arc::new(Semaphore::new(max_concurrent_crawls));
+
+    // GitHub's rate limit is per-token, not per-repo, so every concurrently
+    // crawled repo shares this one budget for `wait_for_budget` to actually
+    // smooth the crawl instead of each repo racing ahead from its own
+    // `usize::MAX`.
+    let github_rate_limit = Arc::new(RateLimit::default());
+
+    let tasks = repos.into_iter().map(|repo| {
+        let semaphore = Arc::clone(&semaphore);
+        let token = token.clone();
+        let gitlab_token = gitlab_token.clone();
+        let cache = Arc::clone(&cache);
+        let github_rate_limit = Arc::clone(&github_rate_limit);
+        tokio::spawn(async move {
+            let _permit = semaphore
+                .acquire_owned()
+                .await
+                .expect("semaphore should never be closed");
+            println!("Fetching {repo}");
+            let provider: Box<dyn StarProvider + Send + Sync> = match repo.host {
+                Host::Github => Box::new(Github::new(
+                    repo.owner.clone(),
+                    repo.name.clone(),
+                    token,
+                    github_rate_limit,
+                )),
+                Host::Gitlab => {
+                    Box::new(Gitlab::new(repo.owner.clone(), repo.name.clone(), gitlab_token))
+                }
+            };
+            let crawler = Crawler::new(provider, MAX_REQUEST_COUNT, use_graphql);
+            let cached = cache.get(&repo.name);
+            let result = crawler.stars(cached).await;
+            (repo, result)
+        })
+    });
 
-    for repo in repos {
-        println!("Fetching {repo}");
-        let crawler = Crawler::new(
-            repo.owner.clone(),
-            repo.name.clone(),
-            token.clone(),
-            MAX_REQUEST_COUNT,
-        );
-        match crawler.stars().await {
-            Ok(stars) => {
+    let mut all_stars = HashMap::new();
+    let mut new_cache = (*cache).clone();
+    let now = date::format_ymd(time::OffsetDateTime::now_utc());
+    for task in join_all(tasks).await {
+        let (repo, result) = task.context("Crawl task panicked")?;
+        match result {
+            Ok(CrawlOutcome::Unchanged(records)) => {
+                // Nothing changed server-side, but refresh `fetched_at` (and
+                // carry the existing `ETag` forward) so the cache doesn't
+                // look stale just because this repo didn't gain any stars.
+                let etag = cache.get(&repo.name).and_then(|entry| entry.etag.clone());
+                new_cache.insert(
+                    repo.name.clone(),
+                    CacheEntry {
+                        etag,
+                        fetched_at: now.clone(),
+                        records: records.clone(),
+                    },
+                );
+                all_stars.insert(repo.name, records);
+            }
+            Ok(CrawlOutcome::Fresh { records, etag }) => {
+                new_cache.insert(
+                    repo.name.clone(),
+                    CacheEntry {
+                        etag,
+                        fetched_at: now.clone(),
+                        records: records.clone(),
+                    },
+                );
                 // Optionally save the stargazers to a JSON file
                 // let path = PathBuf::from(format!("output/{}.json", repo.name));
                 // let json = serde_json::to_string_pretty(&stars)?;
                 // save(&path, json)?;
-                all_stars.insert(repo.name, stars);
+                all_stars.insert(repo.name, records);
             }
             Err(err) => println!("Error for {repo}: {err}"),
         }
     }
+    cache::save(&cache_path, &new_cache)?;
+
+    let downloads = if env::var("ENRICH_CRATES_IO").is_ok() {
+        println!("Looking up crates.io download counts...");
+        let client = reqwest::Client::new();
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_CRATES_IO_LOOKUPS));
+        let tasks = all_stars.keys().cloned().map(|name| {
+            let semaphore = Arc::clone(&semaphore);
+            let client = client.clone();
+            tokio::spawn(async move {
+                let _permit = semaphore
+                    .acquire_owned()
+                    .await
+                    .expect("semaphore should never be closed");
+                let downloads = crates_io::download_counts(&client, &name).await;
+                (name, downloads)
+            })
+        });
+
+        let mut downloads = HashMap::new();
+        for task in join_all(tasks).await {
+            let (name, result) = task.context("crates.io lookup task panicked")?;
+            match result {
+                Ok(Some(counts)) => {
+                    downloads.insert(name, counts);
+                }
+                Ok(None) => {}
+                Err(err) => println!("Error looking up crates.io downloads for {name}: {err}"),
+            }
+        }
+        downloads
+    } else {
+        HashMap::new()
+    };
 
-    let json = serde_json::to_string_pretty(&all_stars)?;
+    let min_stars = env::var("MIN_STARS")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(MIN_STARS);
+    let min_downloads = env::var("MIN_DOWNLOADS")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(MIN_DOWNLOADS);
+
+    let repos: HashMap<String, RepoMetrics> = all_stars
+        .into_iter()
+        .filter_map(|(name, stars)| {
+            let downloads = downloads.get(&name).copied();
+            // With no downloads data for this tool, only `min_stars` applies.
+            let min_downloads = if downloads.is_some() { min_downloads } else { 0 };
+            let current_stars = stars.last().map_or(0, StarRecord::count);
+            let current_downloads = downloads.map_or(0, |d| d.all_time);
+
+            // A threshold of 0 means "not configured", so it can't be the
+            // one thing keeping a tool in the output; only thresholds the
+            // user actually set are weighed below.
+            let clears_stars = min_stars > 0 && current_stars >= min_stars;
+            let clears_downloads = min_downloads > 0 && current_downloads >= min_downloads;
+            if (min_stars > 0 || min_downloads > 0) && !clears_stars && !clears_downloads {
+                // Clearly inactive: below every threshold that was
+                // configured, so drop it from the output instead of
+                // reporting on a dead tool.
+                return None;
+            }
+            Some((name, RepoMetrics { stars, downloads }))
+        })
+        .collect();
+
+    let json = serde_json::to_string_pretty(&repos)?;
     save(&PathBuf::from("stars.json"), json)?;
 
+    if env::var("GENERATE_REPORT").is_ok() {
+        println!("Generating star-history report in {REPORT_DIR}...");
+        report::generate(&repos, &PathBuf::from(REPORT_DIR))?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,52 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::Response;
+
+use crate::crawler::{Star, StarRecord};
+
+/// Result of checking a repo's current star count, conditional on a
+/// previously-cached `ETag`: either nothing has changed since (a `304`), or
+/// a fresh count came back along with the `ETag` to cache for next time.
+///
+/// Unlike the `stargazers` listing (oldest-first, so its first page never
+/// changes for a growing repo), the repo metadata endpoint this comes from
+/// changes its `ETag` the moment the star count does, making it the right
+/// thing to key incremental freshness off.
+#[derive(Debug)]
+pub(crate) enum StarCountOutcome {
+    Unchanged,
+    Fresh { count: usize, etag: Option<String> },
+}
+
+/// A source-control host that can report stargazer history for a repo.
+///
+/// Implemented by [`crate::github::Github`] and [`crate::gitlab::Gitlab`] so
+/// `Crawler` can crawl a repo without caring which host it lives on.
+#[async_trait]
+pub(crate) trait StarProvider {
+    /// Repo owner/namespace, used for display only.
+    fn owner(&self) -> &str;
+
+    /// Fetch the current total star count for the repo, conditional on
+    /// `etag` if set.
+    async fn star_count(&self, etag: Option<&str>) -> Result<StarCountOutcome>;
+
+    /// Fetch one page of stargazers, paginated via the response `Link` header.
+    async fn stargazers(&self, page: Option<usize>) -> Result<Response>;
+
+    /// Parse a `stargazers` response body into host-agnostic `Star` records.
+    async fn parse_stargazers(&self, response: Response) -> Result<Vec<Star>>;
+
+    /// Crawl stargazers via a GraphQL-style API instead of paging the REST
+    /// one, if this provider has one. Returns `Ok(None)` when unsupported, in
+    /// which case `Crawler` falls back to the REST `stargazers` path.
+    ///
+    /// On success, also returns the `totalCount` reported alongside the
+    /// stargazer edges, so callers don't need a separate `star_count` call.
+    async fn graphql_stars(
+        &self,
+        _max_request_count: usize,
+    ) -> Result<Option<(Vec<StarRecord>, usize)>> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tera::{Context as TeraContext, Tera};
+
+use crate::crawler::StarRecord;
+use crate::date;
+use crate::RepoMetrics;
+
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 400.0;
+
+const CHART_TEMPLATE: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="{{ width }}" height="{{ height }}" viewBox="0 0 {{ width }} {{ height }}">
+  <title>{{ name }} star history</title>
+  <polyline fill="none" stroke="#2f81f7" stroke-width="2"
+    points="{% for point in points %}{{ point.x }},{{ point.y }} {% endfor %}" />
+</svg>
+"#;
+
+const INDEX_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Star History</title></head>
+<body>
+<h1>Star History</h1>
+<ul>
+{% for repo in repos %}
+  <li><a href="{{ repo.slug }}.svg">{{ repo.name }}</a> &mdash; {{ repo.stars }} stars
+  {%- if repo.has_downloads %} &mdash; {{ repo.downloads }} crates.io downloads{% endif %}</li>
+{% endfor %}
+</ul>
+</body>
+</html>
+"#;
+
+#[derive(Serialize)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Serialize)]
+struct IndexRepo {
+    name: String,
+    slug: String,
+    stars: usize,
+    downloads: Option<u64>,
+    // Tera treats `0` (and `Some(0)`) as falsy, so `downloads` alone can't
+    // tell the template apart from "no crates.io match" when the count is
+    // genuinely zero; carry presence separately.
+    has_downloads: bool,
+}
+
+/// Turn a repo name into something safe to use as a filename.
+fn slug(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// Render an SVG line chart of cumulative stars over time for one repo.
+///
+/// The x-axis is scaled by each record's actual date, not its position in
+/// the list, since the crawlers sample stargazers unevenly spaced in time
+/// (e.g. the GraphQL path's stride over a capped page walk).
+fn render_chart(name: &str, records: &[StarRecord]) -> Result<String> {
+    let max_count = records.iter().map(StarRecord::count).max().unwrap_or(0).max(1) as f64;
+
+    let days: Vec<f64> = records
+        .iter()
+        .map(|record| date::parse_ymd(record.date()).map(|date| date.to_julian_day() as f64))
+        .collect::<Result<_>>()?;
+    let min_day = days.iter().copied().fold(f64::INFINITY, f64::min);
+    let max_day = days.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let day_span = (max_day - min_day).max(1.0);
+
+    let points: Vec<Point> = records
+        .iter()
+        .zip(&days)
+        .map(|(record, &day)| Point {
+            x: ((day - min_day) / day_span) * CHART_WIDTH,
+            y: CHART_HEIGHT - (record.count() as f64 / max_count) * CHART_HEIGHT,
+        })
+        .collect();
+
+    let mut context = TeraContext::new();
+    context.insert("name", name);
+    context.insert("width", &CHART_WIDTH);
+    context.insert("height", &CHART_HEIGHT);
+    context.insert("points", &points);
+    Tera::one_off(CHART_TEMPLATE, &context, false).context("Failed to render chart template")
+}
+
+/// Render per-repo SVG line charts of cumulative stars, plus an index HTML
+/// page linking to them sorted by current star count.
+///
+/// Repos without crates.io download figures (see [`crate::crates_io`]) are
+/// assumed to not be published crates and simply don't show one.
+pub(crate) fn generate(repos: &HashMap<String, RepoMetrics>, output_dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(output_dir)
+        .context(format!("Failed to create report dir {output_dir:?}"))?;
+
+    let mut index_repos = Vec::new();
+    for (name, metrics) in repos {
+        let svg = render_chart(name, &metrics.stars)?;
+        let slug = slug(name);
+        std::fs::write(output_dir.join(format!("{slug}.svg")), svg)
+            .context(format!("Failed to write chart for {name}"))?;
+
+        let stars = metrics.stars.last().map_or(0, StarRecord::count);
+        index_repos.push(IndexRepo {
+            name: name.clone(),
+            slug,
+            stars,
+            downloads: metrics.downloads.map(|d| d.all_time),
+            has_downloads: metrics.downloads.is_some(),
+        });
+    }
+    index_repos.sort_by(|a, b| b.stars.cmp(&a.stars));
+
+    let mut context = TeraContext::new();
+    context.insert("repos", &index_repos);
+    let index_html =
+        Tera::one_off(INDEX_TEMPLATE, &context, false).context("Failed to render index template")?;
+    std::fs::write(output_dir.join("index.html"), index_html).context("Failed to write index.html")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Anything that isn't ASCII alphanumeric (owner/repo separators,
+    /// punctuation in tool names) becomes a `-`, so the result is always
+    /// safe to use as a filename.
+    fn test_slug() {
+        assert_eq!(slug("rust-lang/rust"), "rust-lang-rust");
+        assert_eq!(slug("foo.bar_baz"), "foo-bar-baz");
+        assert_eq!(slug("already-safe"), "already-safe");
+    }
+}
@@ -2,9 +2,17 @@ use anyhow::{Error, Result};
 use serde_json::Value;
 use std::fmt::{Display, Formatter};
 
+/// Which source-control host a repo's `source` URL points at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Host {
+    Github,
+    Gitlab,
+}
+
 pub(crate) struct Repo {
     pub(crate) owner: String,
     pub(crate) name: String,
+    pub(crate) host: Host,
 }
 
 impl Display for Repo {
@@ -35,16 +43,26 @@ pub(crate) async fn get_repos(tools_json_url: &str) -> Result<Vec<Repo>> {
         .ok_or_else(|| anyhow::anyhow!("Invalid JSON"))?
         .iter()
         .map(|(name, repo)| {
-            let owner = repo
+            let source = repo
                 .get("source")
                 .and_then(serde_json::Value::as_str)
-                .and_then(|source| source.split('/').nth(3))
+                .ok_or_else(|| anyhow::anyhow!("Invalid source URL"))?;
+
+            let mut segments = source.split('/');
+            let host = match segments.nth(2) {
+                Some("github.com") => Host::Github,
+                Some("gitlab.com") => Host::Gitlab,
+                _ => return Err(anyhow::anyhow!("Unsupported or invalid source URL")),
+            };
+            let owner = segments
+                .next()
                 .ok_or_else(|| anyhow::anyhow!("Invalid source URL"))?
                 .to_string();
 
             Ok::<Repo, Error>(Repo {
                 owner,
                 name: name.to_string(),
+                host,
             })
         })
         .filter_map(Result::ok)